@@ -0,0 +1,36 @@
+//! Export of a decoded element stream to a CBOR encoding of the DICOM
+//! JSON model, for compact binary interchange.
+//!
+//! Available behind the `cbor` feature. Shares the tree construction in
+//! `data::element_model` with the JSON front-end in `data::json`, so the
+//! two encodings differ only in the wire format and in how bulk data is
+//! carried: raw bytes in `BinaryValue` here, base64 text in
+//! `InlineBinary` for JSON.
+#![cfg(feature = "cbor")]
+
+use std::io::{Read, Write};
+
+use data::element_model::{self, JsonDataSet};
+use data::DataElement;
+use error::{Error, Result};
+
+/// Consume a stream of decoded elements and write it out as CBOR,
+/// nesting `VR::SQ` elements into arrays of item objects just like
+/// `data::json::to_writer`.
+pub fn to_writer<W, I>(writer: W, elements: I) -> Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = Result<DataElement>>,
+{
+    let data_set = element_model::build_data_set(elements, true)?;
+    ::serde_cbor::to_writer(writer, &data_set).map_err(Error::from)
+}
+
+/// Parse a CBOR-encoded DICOM JSON model document into its tree
+/// representation.
+pub fn from_reader<R>(reader: R) -> Result<JsonDataSet>
+where
+    R: Read,
+{
+    ::serde_cbor::from_reader(reader).map_err(Error::from)
+}
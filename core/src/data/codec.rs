@@ -0,0 +1,299 @@
+//! Paired read/write traits over a single element, abstracting over the
+//! parser/encoder pair so a caller can read a data set with
+//! `DicomElementIterator`, modify selected elements, and write the result
+//! back out with a `data::encoder::Encode`r — the foundation of an
+//! edit-and-save pipeline on top of the otherwise read-only iterators.
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::ops::DerefMut;
+
+use data::encoder::{header_byte_size, Encode};
+use data::iterator::DicomElementMarker;
+use data::parser::Parse;
+use data::value::DicomValue;
+use data::{DataElement, DataElementHeader, Header, SequenceItemHeader};
+use error::{Error, Result};
+use util::ReadSeek;
+
+/// A type that can be decoded from a data set using a `Parse`r, mirroring
+/// the decode methods `DicomElementIterator` already drives internally.
+pub trait DecodeElement<S: ?Sized, P: Parse<S> + ?Sized>: Sized {
+    fn decode_element(parser: &mut P, from: &mut S) -> Result<Self>;
+}
+
+impl<S: ?Sized + Read, P: Parse<S> + ?Sized> DecodeElement<S, P> for DataElementHeader {
+    fn decode_element(parser: &mut P, from: &mut S) -> Result<Self> {
+        parser.decode_header(from)
+    }
+}
+
+impl<S: ?Sized + Read, P: Parse<S> + ?Sized> DecodeElement<S, P> for SequenceItemHeader {
+    fn decode_element(parser: &mut P, from: &mut S) -> Result<Self> {
+        parser.decode_item_header(from)
+    }
+}
+
+impl<S: ?Sized + Read, P: Parse<S> + ?Sized> DecodeElement<S, P> for DataElement {
+    fn decode_element(parser: &mut P, from: &mut S) -> Result<Self> {
+        let header = parser.decode_header(from)?;
+        let value = parser.read_value(from, &header)?;
+        Ok(DataElement { header, value })
+    }
+}
+
+/// A type that can write itself back out using an `Encode`r, the
+/// write-side counterpart to `DecodeElement`.
+pub trait EncodeElement<W: ?Sized, E: Encode<W> + ?Sized> {
+    fn encode_element(&self, encoder: &E, to: &mut W) -> Result<()>;
+}
+
+impl<W: ?Sized + Write, E: Encode<W> + ?Sized> EncodeElement<W, E> for DataElementHeader {
+    fn encode_element(&self, encoder: &E, to: &mut W) -> Result<()> {
+        encoder.encode_header(to, self)
+    }
+}
+
+impl<W: ?Sized + Write, E: Encode<W> + ?Sized> EncodeElement<W, E> for SequenceItemHeader {
+    fn encode_element(&self, encoder: &E, to: &mut W) -> Result<()> {
+        encoder.encode_item_header(to, self)
+    }
+}
+
+impl<W: ?Sized + Write, E: Encode<W> + ?Sized> EncodeElement<W, E> for DataElement {
+    fn encode_element(&self, encoder: &E, to: &mut W) -> Result<()> {
+        encoder.encode_header(to, &self.header)?;
+        match self.value {
+            // Item/delimiter markers and zero-length elements carry no
+            // value bytes, so they always round-trip losslessly.
+            DicomValue::Empty => Ok(()),
+            _ => Err(Error::from(::std::io::Error::new(
+                ::std::io::ErrorKind::Other,
+                format!(
+                    "re-encoding a populated {:?} value for tag {:?} requires a byte-level \
+                     accessor on DicomValue that does not exist yet; use EncodableElement (via \
+                     EncodableElement::from_marker) to carry the original raw bytes through \
+                     instead for elements left unmodified",
+                    self.header.vr(),
+                    self.header.tag()
+                ),
+            ))),
+        }
+    }
+}
+
+/// An element paired with the raw, already-encoded bytes of its value,
+/// ready to be written back out verbatim. This is the write side's
+/// answer to the fact that `DicomValue` has no byte-level accessor yet:
+/// rather than re-deriving an element's wire bytes from its decoded
+/// value, `from_marker` captures them once, up front, straight off the
+/// source the element was read from.
+#[derive(Debug, Clone)]
+pub struct EncodableElement {
+    pub header: DataElementHeader,
+    pub raw_value: Vec<u8>,
+}
+
+impl EncodableElement {
+    /// Capture an element's header and raw value bytes as they appear in
+    /// `source`, via its marker. Used to carry an unmodified element
+    /// through a read-modify-write pipeline without needing to
+    /// understand its decoded `DicomValue` at all.
+    pub fn from_marker<S: ?Sized, B: DerefMut<Target = S>>(
+        marker: &DicomElementMarker,
+        source: B,
+    ) -> Result<EncodableElement>
+    where
+        S: ReadSeek,
+    {
+        let mut raw_value = Vec::with_capacity(marker.header.len() as usize);
+        marker.get_data_stream(source)?.read_to_end(&mut raw_value)?;
+        Ok(EncodableElement {
+            header: marker.header,
+            raw_value: raw_value,
+        })
+    }
+}
+
+impl<W: ?Sized + Write, E: Encode<W> + ?Sized> EncodeElement<W, E> for EncodableElement {
+    fn encode_element(&self, encoder: &E, to: &mut W) -> Result<()> {
+        encoder.encode_header(to, &self.header)?;
+        encoder.write_value(to, &self.raw_value)
+    }
+}
+
+/// One item of a stream to be written back out by `rewrite`: either a
+/// populated data element, or one of the three structural markers
+/// (`Item`, `ItemDelimiter`, `SequenceDelimiter`) that punctuate an
+/// undefined-length sequence. The two need different wire formats — an
+/// element header carries a VR (and for long-form VRs, two reserved
+/// bytes), a sequence item header never does — so `EncodableElement`
+/// alone cannot represent a marker; this type lets `rewrite()` route
+/// each kind through the right `Encode` method.
+#[derive(Debug, Clone)]
+pub enum EncodableItem {
+    Element(EncodableElement),
+    Marker(SequenceItemHeader),
+}
+
+impl<W: ?Sized + Write, E: Encode<W> + ?Sized> EncodeElement<W, E> for EncodableItem {
+    fn encode_element(&self, encoder: &E, to: &mut W) -> Result<()> {
+        match *self {
+            EncodableItem::Element(ref element) => element.encode_element(encoder, to),
+            EncodableItem::Marker(ref header) => encoder.encode_item_header(to, header),
+        }
+    }
+}
+
+/// Recompute every group length element's declared length from its
+/// siblings, in place. A group length element is the one with tag
+/// `(group, 0x0000)`; its value is the total encoded byte size —
+/// header plus declared value length — of every other element sharing
+/// that group. Item/item-delimiter/sequence-delimiter markers are not
+/// members of any group for this purpose (per the standard, they're
+/// excluded from group length calculations), so they're skipped in both
+/// passes.
+///
+/// A group length element's own value is always a single `UL`, i.e.
+/// exactly 4 bytes; only the 4 bytes of `raw_value` carry the computed
+/// total; `header.len` is set to 4 rather than to the (much larger)
+/// group total.
+pub fn recompute_group_lengths(items: &mut [EncodableItem]) {
+    let mut totals: HashMap<u16, u32> = HashMap::new();
+    for item in items.iter() {
+        if let EncodableItem::Element(ref element) = *item {
+            let tag = element.header.tag();
+            if tag.1 != 0 {
+                *totals.entry(tag.0).or_insert(0) +=
+                    header_byte_size(element.header.vr()) + element.header.len();
+            }
+        }
+    }
+
+    for item in items.iter_mut() {
+        if let EncodableItem::Element(ref mut element) = *item {
+            let tag = element.header.tag();
+            if tag.1 == 0 {
+                if let Some(&total) = totals.get(&tag.0) {
+                    element.header.len = 4;
+                    element.raw_value = total.to_le_bytes().to_vec();
+                }
+            }
+        }
+    }
+}
+
+/// Write a whole data set back out, first recomputing group lengths and
+/// then encoding every item in order. Undefined-length sequences are
+/// written correctly because their item and (item/sequence) delimiters
+/// are carried as `EncodableItem::Marker`s, not `EncodableElement`s, so
+/// they go through `Encode::encode_item_header` — the bare tag + 4-byte
+/// length the wire format actually uses for them — instead of picking up
+/// a spurious VR (and, for long-form VRs, reserved bytes) from
+/// `encode_header`.
+pub fn rewrite<W, E>(items: &mut [EncodableItem], encoder: &E, to: &mut W) -> Result<()>
+where
+    W: Write,
+    E: Encode<W>,
+{
+    recompute_group_lengths(items);
+    for item in items.iter() {
+        item.encode_element(encoder, to)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::{Tag, VR};
+
+    fn element(tag: Tag, vr: VR, len: u32) -> EncodableItem {
+        EncodableItem::Element(EncodableElement {
+            header: DataElementHeader { tag: tag, vr: vr, len: len },
+            raw_value: vec![0u8; len as usize],
+        })
+    }
+
+    #[test]
+    fn recompute_group_lengths_sums_sibling_byte_sizes() {
+        let mut items = vec![
+            element(Tag(0x0008, 0x0000), VR::UL, 0),
+            // short form: 8-byte header + 10-byte value = 18
+            element(Tag(0x0008, 0x0005), VR::CS, 10),
+            // long form: 12-byte header + 4-byte value = 16
+            element(Tag(0x0008, 0x0008), VR::OB, 4),
+        ];
+
+        recompute_group_lengths(&mut items);
+
+        match items[0] {
+            EncodableItem::Element(ref element) => {
+                assert_eq!(element.header.len, 4);
+                assert_eq!(element.raw_value, 34u32.to_le_bytes().to_vec());
+            }
+            EncodableItem::Marker(_) => panic!("expected an Element"),
+        }
+    }
+
+    #[test]
+    fn recompute_group_lengths_ignores_item_markers() {
+        let mut items = vec![
+            element(Tag(0x0008, 0x0000), VR::UL, 0),
+            element(Tag(0x0008, 0x0005), VR::CS, 10),
+            EncodableItem::Marker(SequenceItemHeader::Item { len: 10 }),
+            EncodableItem::Marker(SequenceItemHeader::ItemDelimiter),
+        ];
+
+        // Must not panic matching a Marker as an Element, and must not
+        // fold the markers' own bytes into the 0008 group total.
+        recompute_group_lengths(&mut items);
+
+        match items[0] {
+            EncodableItem::Element(ref element) => {
+                assert_eq!(element.raw_value, 18u32.to_le_bytes().to_vec())
+            }
+            EncodableItem::Marker(_) => panic!("expected an Element"),
+        }
+    }
+
+    #[test]
+    fn encodable_item_marker_writes_no_vr() {
+        struct RecordingEncoder {
+            wrote_vr: ::std::cell::RefCell<bool>,
+        }
+
+        impl Encode<Vec<u8>> for RecordingEncoder {
+            fn encode_header(&self, _to: &mut Vec<u8>, _header: &DataElementHeader) -> Result<()> {
+                *self.wrote_vr.borrow_mut() = true;
+                Ok(())
+            }
+
+            fn encode_item_header(
+                &self,
+                to: &mut Vec<u8>,
+                header: &SequenceItemHeader,
+            ) -> Result<()> {
+                let len = match *header {
+                    SequenceItemHeader::Item { len } => len,
+                    SequenceItemHeader::ItemDelimiter => 0,
+                    SequenceItemHeader::SequenceDelimiter => 0,
+                };
+                to.extend_from_slice(&len.to_le_bytes());
+                Ok(())
+            }
+
+            fn write_value(&self, to: &mut Vec<u8>, bytes: &[u8]) -> Result<()> {
+                to.extend_from_slice(bytes);
+                Ok(())
+            }
+        }
+
+        let encoder = RecordingEncoder { wrote_vr: ::std::cell::RefCell::new(false) };
+        let mut out = Vec::new();
+        let item = EncodableItem::Marker(SequenceItemHeader::ItemDelimiter);
+
+        item.encode_element(&encoder, &mut out).unwrap();
+
+        assert!(!*encoder.wrote_vr.borrow());
+    }
+}
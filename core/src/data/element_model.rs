@@ -0,0 +1,477 @@
+//! A serde-friendly tree representation of a DICOM data set, shared by
+//! the JSON (`data::json`) and CBOR (`data::cbor`) front-ends. Building
+//! this tree once and handing it to either `serde_json` or `serde_cbor`
+//! keeps the two encodings in lock-step, instead of duplicating the
+//! sequence-nesting logic per format.
+#![cfg(feature = "serde")]
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use data::index::{ITEM_DELIMITER_TAG, ITEM_TAG, SEQUENCE_DELIMITER_TAG};
+use data::value::DicomValue;
+use data::{DataElement, DataElementHeader, Header, Tag, VR};
+use error::{Error, Result};
+
+/// The length field value marking an element or item whose extent is
+/// delimited by a matching delimiter element rather than stated upfront.
+const UNDEFINED_LENGTH: u32 = 0xFFFF_FFFF;
+
+/// A single value, as carried by the `Value` array of a `JsonElement`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum JsonPrimitive {
+    Str(String),
+    Number(f64),
+}
+
+/// The body of a `JsonElement`'s `Value` field: either a flat list of
+/// primitives, for ordinary elements, or a list of nested item data sets,
+/// for a sequence (`VR::SQ`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum JsonValueBody {
+    Items(Vec<BTreeMap<String, JsonElement>>),
+    Primitives(Vec<JsonPrimitive>),
+}
+
+/// One element of the DICOM JSON/CBOR model: a `vr` tag plus, depending
+/// on the value representation, either a `Value` array, a `BinaryValue`
+/// (raw bytes, used by the CBOR encoding) or an `InlineBinary` (base64,
+/// used by the JSON encoding).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JsonElement {
+    pub vr: String,
+    #[serde(rename = "Value", skip_serializing_if = "Option::is_none", default)]
+    pub value: Option<JsonValueBody>,
+    #[serde(rename = "BinaryValue", skip_serializing_if = "Option::is_none", default)]
+    pub binary_value: Option<Vec<u8>>,
+    #[serde(rename = "InlineBinary", skip_serializing_if = "Option::is_none", default)]
+    pub inline_binary: Option<String>,
+}
+
+/// A full data set, keyed by the 8-hex-digit tag of each of its
+/// top-level elements.
+pub type JsonDataSet = BTreeMap<String, JsonElement>;
+
+fn tag_key(tag: Tag) -> String {
+    format!("{:04X}{:04X}", tag.0, tag.1)
+}
+
+/// Frame kept per open sequence while folding a flat element stream into
+/// a `JsonDataSet` tree.
+struct OpenSequence {
+    vr: VR,
+    items: Vec<JsonDataSet>,
+    current_item: Option<JsonDataSet>,
+}
+
+/// Consume a flat stream of decoded elements (as yielded by
+/// `DicomElementIterator`) and fold it into a nested `JsonDataSet`,
+/// opening a new item list whenever a `VR::SQ` element is seen and
+/// closing it again on the matching sequence delimiter.
+pub fn build_data_set<I>(elements: I, raw_binary: bool) -> Result<JsonDataSet>
+where
+    I: IntoIterator<Item = Result<DataElement>>,
+{
+    let mut root = JsonDataSet::new();
+    let mut stack: Vec<(String, OpenSequence)> = Vec::new();
+
+    for element in elements {
+        let element = element?;
+        let tag = element.header.tag();
+
+        match tag {
+            ITEM_TAG => {
+                if let Some(&mut (_, ref mut seq)) = stack.last_mut() {
+                    seq.current_item = Some(JsonDataSet::new());
+                }
+            }
+            ITEM_DELIMITER_TAG => {
+                if let Some(&mut (_, ref mut seq)) = stack.last_mut() {
+                    if let Some(item) = seq.current_item.take() {
+                        seq.items.push(item);
+                    }
+                }
+            }
+            SEQUENCE_DELIMITER_TAG => {
+                if let Some((key, seq)) = stack.pop() {
+                    let json_element = JsonElement {
+                        vr: format!("{:?}", seq.vr),
+                        value: Some(JsonValueBody::Items(seq.items)),
+                        binary_value: None,
+                        inline_binary: None,
+                    };
+                    insert(&mut root, &mut stack, key, json_element);
+                }
+            }
+            _ => {
+                let vr = element.header.vr();
+                let key = tag_key(tag);
+
+                if vr == VR::SQ {
+                    stack.push((
+                        key,
+                        OpenSequence {
+                            vr: vr,
+                            items: Vec::new(),
+                            current_item: None,
+                        },
+                    ));
+                } else {
+                    let json_element = to_json_element(&element, raw_binary);
+                    insert(&mut root, &mut stack, key, json_element);
+                }
+            }
+        }
+    }
+
+    Ok(root)
+}
+
+fn insert(
+    root: &mut JsonDataSet,
+    stack: &mut Vec<(String, OpenSequence)>,
+    key: String,
+    element: JsonElement,
+) {
+    match stack.last_mut() {
+        Some(&mut (_, ref mut seq)) => {
+            if let Some(ref mut item) = seq.current_item {
+                item.insert(key, element);
+            }
+        }
+        None => {
+            root.insert(key, element);
+        }
+    }
+}
+
+/// Convert a decoded, non-sequence `DataElement` into its JSON/CBOR
+/// model representation. The choice is made by the decoded value, not by
+/// the VR: a `DicomValue::U8` value carries its bytes as `BinaryValue`
+/// when `raw_binary` is set (the CBOR encoding) or as base64
+/// `InlineBinary` otherwise (the JSON encoding); every other value gets a
+/// `Value` array of the element's actual typed values, not a
+/// debug-formatted placeholder. A bulk-data VR such as `OW` or `OF` whose
+/// value was decoded into numbers therefore gets a `Value` array.
+fn to_json_element(element: &DataElement, raw_binary: bool) -> JsonElement {
+    let vr = element.header.vr();
+    let vr_string = format!("{:?}", vr);
+
+    match element.value {
+        DicomValue::Empty => JsonElement {
+            vr: vr_string,
+            value: None,
+            binary_value: None,
+            inline_binary: None,
+        },
+        DicomValue::U8(ref bytes) => {
+            if raw_binary {
+                JsonElement {
+                    vr: vr_string,
+                    value: None,
+                    binary_value: Some(bytes.clone()),
+                    inline_binary: None,
+                }
+            } else {
+                JsonElement {
+                    vr: vr_string,
+                    value: None,
+                    binary_value: None,
+                    inline_binary: Some(::base64::encode(bytes)),
+                }
+            }
+        }
+        DicomValue::Str(ref s) => primitives(vr_string, vec![JsonPrimitive::Str(s.clone())]),
+        DicomValue::Strs(ref strs) => primitives(
+            vr_string,
+            strs.iter().cloned().map(JsonPrimitive::Str).collect(),
+        ),
+        DicomValue::U16(ref v) => primitives(vr_string, numbers(v)),
+        DicomValue::I16(ref v) => primitives(vr_string, numbers(v)),
+        DicomValue::U32(ref v) => primitives(vr_string, numbers(v)),
+        DicomValue::I32(ref v) => primitives(vr_string, numbers(v)),
+        DicomValue::F32(ref v) => primitives(vr_string, numbers(v)),
+        DicomValue::F64(ref v) => primitives(vr_string, numbers(v)),
+    }
+}
+
+fn numbers<T: Copy + Into<f64>>(values: &[T]) -> Vec<JsonPrimitive> {
+    values
+        .iter()
+        .map(|&n| JsonPrimitive::Number(n.into()))
+        .collect()
+}
+
+fn primitives(vr: String, values: Vec<JsonPrimitive>) -> JsonElement {
+    JsonElement {
+        vr: vr,
+        value: Some(JsonValueBody::Primitives(values)),
+        binary_value: None,
+        inline_binary: None,
+    }
+}
+
+/// Parse a VR code, the write side's counterpart to the
+/// `format!("{:?}", vr)` used by `to_json_element`.
+fn parse_vr(code: &str) -> Result<VR> {
+    match code {
+        "AE" => Ok(VR::AE), "AS" => Ok(VR::AS), "AT" => Ok(VR::AT), "CS" => Ok(VR::CS),
+        "DA" => Ok(VR::DA), "DS" => Ok(VR::DS), "DT" => Ok(VR::DT), "FL" => Ok(VR::FL),
+        "FD" => Ok(VR::FD), "IS" => Ok(VR::IS), "LO" => Ok(VR::LO), "LT" => Ok(VR::LT),
+        "OB" => Ok(VR::OB), "OF" => Ok(VR::OF), "OW" => Ok(VR::OW), "PN" => Ok(VR::PN),
+        "SH" => Ok(VR::SH), "SL" => Ok(VR::SL), "SQ" => Ok(VR::SQ), "SS" => Ok(VR::SS),
+        "ST" => Ok(VR::ST), "TM" => Ok(VR::TM), "UI" => Ok(VR::UI), "UL" => Ok(VR::UL),
+        "UN" => Ok(VR::UN), "US" => Ok(VR::US), "UT" => Ok(VR::UT),
+        _ => Err(Error::from(::std::io::Error::new(
+            ::std::io::ErrorKind::InvalidData,
+            format!("unrecognized VR code {:?}", code),
+        ))),
+    }
+}
+
+fn parse_tag_key(key: &str) -> Result<Tag> {
+    if key.len() != 8 {
+        return Err(Error::from(::std::io::Error::new(
+            ::std::io::ErrorKind::InvalidData,
+            format!("tag key {:?} is not 8 hex digits", key),
+        )));
+    }
+    let invalid = || {
+        Error::from(::std::io::Error::new(
+            ::std::io::ErrorKind::InvalidData,
+            format!("tag key {:?} is not a valid hex group/element pair", key),
+        ))
+    };
+    let group = u16::from_str_radix(&key[0..4], 16).map_err(|_| invalid())?;
+    let element = u16::from_str_radix(&key[4..8], 16).map_err(|_| invalid())?;
+    Ok(Tag(group, element))
+}
+
+fn value_to_dicom_value(vr: VR, element: &JsonElement) -> Result<DicomValue> {
+    if let Some(ref bytes) = element.binary_value {
+        return Ok(DicomValue::U8(bytes.clone()));
+    }
+    if let Some(ref inline) = element.inline_binary {
+        let bytes = ::base64::decode(inline).map_err(|e| {
+            Error::from(::std::io::Error::new(::std::io::ErrorKind::InvalidData, e.to_string()))
+        })?;
+        return Ok(DicomValue::U8(bytes));
+    }
+    let primitives = match element.value {
+        Some(JsonValueBody::Primitives(ref primitives)) => primitives,
+        Some(JsonValueBody::Items(_)) => {
+            return Err(Error::from(::std::io::Error::new(
+                ::std::io::ErrorKind::InvalidData,
+                "sequence elements are flattened by flatten_data_set, not value_to_dicom_value",
+            )))
+        }
+        None => return Ok(DicomValue::Empty),
+    };
+
+    match vr {
+        VR::US => Ok(DicomValue::U16(
+            primitives.iter().map(|p| as_f64(p).map(|n| n as u16)).collect::<Result<_>>()?,
+        )),
+        VR::SS => Ok(DicomValue::I16(
+            primitives.iter().map(|p| as_f64(p).map(|n| n as i16)).collect::<Result<_>>()?,
+        )),
+        VR::UL | VR::AT => Ok(DicomValue::U32(
+            primitives.iter().map(|p| as_f64(p).map(|n| n as u32)).collect::<Result<_>>()?,
+        )),
+        VR::SL => Ok(DicomValue::I32(
+            primitives.iter().map(|p| as_f64(p).map(|n| n as i32)).collect::<Result<_>>()?,
+        )),
+        VR::FL => Ok(DicomValue::F32(
+            primitives.iter().map(|p| as_f64(p).map(|n| n as f32)).collect::<Result<_>>()?,
+        )),
+        VR::FD | VR::DS => {
+            Ok(DicomValue::F64(primitives.iter().map(as_f64).collect::<Result<_>>()?))
+        }
+        _ => {
+            let strs: Vec<String> = primitives.iter().map(as_str).collect::<Result<_>>()?;
+            if strs.len() == 1 {
+                Ok(DicomValue::Str(strs.into_iter().next().unwrap()))
+            } else {
+                Ok(DicomValue::Strs(strs))
+            }
+        }
+    }
+}
+
+fn as_f64(primitive: &JsonPrimitive) -> Result<f64> {
+    match *primitive {
+        JsonPrimitive::Number(n) => Ok(n),
+        JsonPrimitive::Str(_) => Err(Error::from(::std::io::Error::new(
+            ::std::io::ErrorKind::InvalidData,
+            "expected a numeric Value entry, found a string",
+        ))),
+    }
+}
+
+fn as_str(primitive: &JsonPrimitive) -> Result<String> {
+    match *primitive {
+        JsonPrimitive::Str(ref s) => Ok(s.clone()),
+        JsonPrimitive::Number(_) => Err(Error::from(::std::io::Error::new(
+            ::std::io::ErrorKind::InvalidData,
+            "expected a string Value entry, found a number",
+        ))),
+    }
+}
+
+/// Reconstruct a flat stream of `DataElement`s from a parsed
+/// `JsonDataSet`. Sequence items are re-flattened into item/delimiter
+/// markers, mirroring the shape produced by `DicomElementIterator`; since
+/// the model already carries typed values (or raw/base64 bytes), no
+/// transfer syntax is needed here — only `data::encoder::Encode`-ing the
+/// result back onto the wire does.
+pub fn flatten_data_set(data_set: &JsonDataSet) -> Result<Vec<DataElement>> {
+    let mut out = Vec::new();
+    flatten_into(data_set, &mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_json_element_uses_typed_values_not_debug_text() {
+        let element = DataElement {
+            header: DataElementHeader { tag: Tag(0x0028, 0x0010), vr: VR::US, len: 2 },
+            value: DicomValue::U16(vec![512]),
+        };
+
+        let json_element = to_json_element(&element, false);
+        assert_eq!(json_element.vr, "US");
+        assert_eq!(
+            json_element.value,
+            Some(JsonValueBody::Primitives(vec![JsonPrimitive::Number(512.0)]))
+        );
+    }
+
+    #[test]
+    fn to_json_element_base64_encodes_bulk_data_for_json() {
+        let element = DataElement {
+            header: DataElementHeader { tag: Tag(0x7FE0, 0x0010), vr: VR::OB, len: 2 },
+            value: DicomValue::U8(vec![0xDE, 0xAD]),
+        };
+
+        let json_element = to_json_element(&element, false);
+        assert_eq!(json_element.inline_binary, Some(::base64::encode(&[0xDEu8, 0xAD])));
+        assert!(json_element.binary_value.is_none());
+    }
+
+    #[test]
+    fn to_json_element_keeps_raw_bytes_for_cbor() {
+        let element = DataElement {
+            header: DataElementHeader { tag: Tag(0x7FE0, 0x0010), vr: VR::OB, len: 2 },
+            value: DicomValue::U8(vec![0xDE, 0xAD]),
+        };
+
+        let json_element = to_json_element(&element, true);
+        assert_eq!(json_element.binary_value, Some(vec![0xDE, 0xAD]));
+        assert!(json_element.inline_binary.is_none());
+    }
+
+    #[test]
+    fn flatten_data_set_round_trips_a_plain_value() {
+        let mut data_set = JsonDataSet::new();
+        data_set.insert(
+            "00280010".to_string(),
+            JsonElement {
+                vr: "US".to_string(),
+                value: Some(JsonValueBody::Primitives(vec![JsonPrimitive::Number(512.0)])),
+                binary_value: None,
+                inline_binary: None,
+            },
+        );
+
+        let elements = flatten_data_set(&data_set).unwrap();
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].header.tag, Tag(0x0028, 0x0010));
+        match elements[0].value {
+            DicomValue::U16(ref v) => assert_eq!(v, &[512]),
+            _ => panic!("expected DicomValue::U16"),
+        }
+    }
+
+    #[test]
+    fn flatten_data_set_expands_a_sequence_into_item_delimiters() {
+        let mut item = JsonDataSet::new();
+        item.insert(
+            "00080005".to_string(),
+            JsonElement {
+                vr: "CS".to_string(),
+                value: Some(JsonValueBody::Primitives(vec![JsonPrimitive::Str("ISO_IR 100".to_string())])),
+                binary_value: None,
+                inline_binary: None,
+            },
+        );
+
+        let mut data_set = JsonDataSet::new();
+        data_set.insert(
+            "00081140".to_string(),
+            JsonElement {
+                vr: "SQ".to_string(),
+                value: Some(JsonValueBody::Items(vec![item])),
+                binary_value: None,
+                inline_binary: None,
+            },
+        );
+
+        let elements = flatten_data_set(&data_set).unwrap();
+        // SQ header, ITEM, child element, ITEM_DELIMITER, SEQUENCE_DELIMITER
+        assert_eq!(elements.len(), 5);
+        assert_eq!(elements[0].header.tag, Tag(0x0008, 0x1140));
+        assert_eq!(elements[1].header.tag, ITEM_TAG);
+        assert_eq!(elements[2].header.tag, Tag(0x0008, 0x0005));
+        assert_eq!(elements[3].header.tag, ITEM_DELIMITER_TAG);
+        assert_eq!(elements[4].header.tag, SEQUENCE_DELIMITER_TAG);
+    }
+}
+
+fn flatten_into(data_set: &JsonDataSet, out: &mut Vec<DataElement>) -> Result<()> {
+    for (key, element) in data_set {
+        let tag = parse_tag_key(key)?;
+        let vr = parse_vr(&element.vr)?;
+
+        match element.value {
+            Some(JsonValueBody::Items(ref items)) => {
+                out.push(DataElement {
+                    header: DataElementHeader { tag: tag, vr: vr, len: UNDEFINED_LENGTH },
+                    value: DicomValue::Empty,
+                });
+                for item in items {
+                    out.push(DataElement {
+                        header: DataElementHeader {
+                            tag: ITEM_TAG,
+                            vr: VR::UN,
+                            len: UNDEFINED_LENGTH,
+                        },
+                        value: DicomValue::Empty,
+                    });
+                    flatten_into(item, out)?;
+                    out.push(DataElement {
+                        header: DataElementHeader { tag: ITEM_DELIMITER_TAG, vr: VR::UN, len: 0 },
+                        value: DicomValue::Empty,
+                    });
+                }
+                out.push(DataElement {
+                    header: DataElementHeader { tag: SEQUENCE_DELIMITER_TAG, vr: VR::UN, len: 0 },
+                    value: DicomValue::Empty,
+                });
+            }
+            _ => {
+                let value = value_to_dicom_value(vr, element)?;
+                out.push(DataElement {
+                    header: DataElementHeader { tag: tag, vr: vr, len: 0 },
+                    value: value,
+                });
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! The write-side counterpart to `data::parser`: encodes headers back
+//! onto a `Write` destination, mirroring `Parse` so that `data::codec`
+//! can pair the two into a read-modify-write pipeline.
+//!
+//! Value bytes are written as-is (see `Encode::write_value`): callers
+//! that keep an element's value unchanged can pass through the raw bytes
+//! obtained via `DicomElementMarker::get_data_stream`, while a value
+//! that was freshly constructed in memory needs its own byte-level
+//! packing, which is left to a future extension of `DicomValue`.
+use std::io::Write;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use data::{DataElementHeader, Header, SequenceItemHeader, Tag, VR};
+use error::Result;
+
+/// Mirrors `data::parser::Parse`, but for writing a data set back out
+/// instead of reading one in.
+pub trait Encode<W: ?Sized> {
+    /// Write a data element header in explicit VR, little endian form.
+    fn encode_header(&self, to: &mut W, header: &DataElementHeader) -> Result<()>;
+
+    /// Write a sequence item header (item, item delimiter or sequence
+    /// delimiter).
+    fn encode_item_header(&self, to: &mut W, header: &SequenceItemHeader) -> Result<()>;
+
+    /// Write `bytes` verbatim as an element's value payload. The caller
+    /// is responsible for making sure `bytes.len()` matches the `len`
+    /// already written via `encode_header`.
+    fn write_value(&self, to: &mut W, bytes: &[u8]) -> Result<()>;
+}
+
+/// The value representations that use the 4-byte "long" length form
+/// (2 reserved bytes followed by a 4-byte length), per the explicit VR
+/// little endian transfer syntax.
+fn is_long_form_vr(vr: VR) -> bool {
+    match vr {
+        VR::OB | VR::OW | VR::OF | VR::SQ | VR::UN | VR::UT => true,
+        _ => false,
+    }
+}
+
+/// The number of header bytes (tag + VR + length) an element with the
+/// given VR occupies under explicit VR little endian encoding.
+pub fn header_byte_size(vr: VR) -> u32 {
+    if is_long_form_vr(vr) {
+        12
+    } else {
+        8
+    }
+}
+
+fn vr_code(vr: VR) -> [u8; 2] {
+    let code: &'static str = match vr {
+        VR::AE => "AE", VR::AS => "AS", VR::AT => "AT", VR::CS => "CS",
+        VR::DA => "DA", VR::DS => "DS", VR::DT => "DT", VR::FL => "FL",
+        VR::FD => "FD", VR::IS => "IS", VR::LO => "LO", VR::LT => "LT",
+        VR::OB => "OB", VR::OF => "OF", VR::OW => "OW", VR::PN => "PN",
+        VR::SH => "SH", VR::SL => "SL", VR::SQ => "SQ", VR::SS => "SS",
+        VR::ST => "ST", VR::TM => "TM", VR::UI => "UI", VR::UL => "UL",
+        VR::UN => "UN", VR::US => "US", VR::UT => "UT",
+    };
+    let bytes = code.as_bytes();
+    [bytes[0], bytes[1]]
+}
+
+/// An encoder that always writes explicit VR, little endian headers.
+/// This is the write-side analogue of `DicomParser`/`DynamicDicomParser`;
+/// support for additional transfer syntaxes (implicit VR, big endian)
+/// belongs here as further variants once needed.
+#[derive(Debug, Default)]
+pub struct DicomEncoder;
+
+impl<W: ?Sized + Write> Encode<W> for DicomEncoder {
+    fn encode_header(&self, to: &mut W, header: &DataElementHeader) -> Result<()> {
+        let Tag(group, element) = header.tag();
+        to.write_u16::<LittleEndian>(group)?;
+        to.write_u16::<LittleEndian>(element)?;
+        to.write_all(&vr_code(header.vr()))?;
+        if is_long_form_vr(header.vr()) {
+            to.write_u16::<LittleEndian>(0)?;
+            to.write_u32::<LittleEndian>(header.len())?;
+        } else {
+            to.write_u16::<LittleEndian>(header.len() as u16)?;
+        }
+        Ok(())
+    }
+
+    fn encode_item_header(&self, to: &mut W, header: &SequenceItemHeader) -> Result<()> {
+        let (tag, len) = match *header {
+            SequenceItemHeader::Item { len } => (Tag(0xFFFE, 0xE000), len),
+            SequenceItemHeader::ItemDelimiter => (Tag(0xFFFE, 0xE00D), 0),
+            SequenceItemHeader::SequenceDelimiter => (Tag(0xFFFE, 0xE0DD), 0),
+        };
+        to.write_u16::<LittleEndian>(tag.0)?;
+        to.write_u16::<LittleEndian>(tag.1)?;
+        to.write_u32::<LittleEndian>(len)?;
+        Ok(())
+    }
+
+    fn write_value(&self, to: &mut W, bytes: &[u8]) -> Result<()> {
+        to.write_all(bytes).map_err(From::from)
+    }
+}
@@ -0,0 +1,319 @@
+//! A prebuilt index over a DICOM data set's element markers, for jumping
+//! directly to a named element instead of walking the stream from the
+//! start every time.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use std::io::Seek;
+
+use data::iterator::{DicomElementMarker, LazyDicomElementIterator};
+use data::parser::DynamicDicomParser;
+use data::text::SpecificCharacterSet;
+use data::{Header, Tag};
+use error::{Error, Result};
+use transfer_syntax::TransferSyntax;
+use util::ReadSeek;
+
+/// The tag used by the standard to mark the start of a sequence item.
+pub(crate) const ITEM_TAG: Tag = Tag(0xFFFE, 0xE000);
+/// The tag used to mark the end of an undefined-length item.
+pub(crate) const ITEM_DELIMITER_TAG: Tag = Tag(0xFFFE, 0xE00D);
+/// The tag used to mark the end of an undefined-length sequence.
+pub(crate) const SEQUENCE_DELIMITER_TAG: Tag = Tag(0xFFFE, 0xE0DD);
+
+/// Frame kept for each sequence that is currently open while indexing.
+struct OpenSequence {
+    /// The path of the sequence element itself (its last segment has
+    /// `item: None`).
+    path: TagPath,
+    /// The index of the next item to be read.
+    next_item: u32,
+    /// The path under which children of the currently open item (if
+    /// any) should be inserted.
+    item_container: Option<TagPath>,
+}
+
+/// One segment of a tag path, identifying either a plain element or a
+/// particular item of a sequence (e.g. `(0008,1140)[0]`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PathSegment {
+    /// The tag of the element or sequence this segment refers to.
+    pub tag: Tag,
+    /// The item index within the sequence, if this segment addresses a
+    /// sequence item rather than the sequence element itself.
+    pub item: Option<u32>,
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({:04X},{:04X})", self.tag.0, self.tag.1)?;
+        if let Some(item) = self.item {
+            write!(f, "[{}]", item)?;
+        }
+        Ok(())
+    }
+}
+
+/// A full tag path, e.g. `(0008,1140)[0]/(0008,1155)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TagPath(pub Vec<PathSegment>);
+
+impl fmt::Display for TagPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut segments = self.0.iter();
+        if let Some(first) = segments.next() {
+            write!(f, "{}", first)?;
+        }
+        for segment in segments {
+            write!(f, "/{}", segment)?;
+        }
+        Ok(())
+    }
+}
+
+impl TagPath {
+    fn child(&self, segment: PathSegment) -> TagPath {
+        let mut path = self.0.clone();
+        path.push(segment);
+        TagPath(path)
+    }
+
+    /// Clone this path, setting the item index on its own last segment
+    /// rather than appending a new one — used to address "item `n` of
+    /// this sequence" without duplicating the sequence's tag.
+    fn with_item(&self, item: u32) -> TagPath {
+        let mut path = self.clone();
+        if let Some(last) = path.0.last_mut() {
+            last.item = Some(item);
+        }
+        path
+    }
+}
+
+/// The byte range, relative to the start of the source, occupied by one
+/// item of a sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemRange {
+    /// The position of the item's data, as recorded on its marker.
+    pub start: u64,
+    /// The position immediately after the item's delimiter, as recorded
+    /// on the delimiter's marker (the item's contents end 8 bytes
+    /// earlier). `None` for an item whose delimiter has not been
+    /// observed, which includes every item of defined length.
+    pub end: Option<u64>,
+}
+
+/// A node of the `DatasetIndex` tree, mirroring the sequence nesting
+/// tracked by the element iterator while it was indexed.
+#[derive(Debug, Clone)]
+pub struct IndexNode {
+    /// The marker for the element at this path.
+    pub marker: DicomElementMarker,
+    /// For a sequence node, the byte range of each of its items, in
+    /// encounter order.
+    pub item_ranges: Vec<ItemRange>,
+    /// Direct children of this node, keyed by their full tag path.
+    pub children: BTreeMap<TagPath, IndexNode>,
+}
+
+impl IndexNode {
+    fn new(marker: DicomElementMarker) -> Self {
+        IndexNode {
+            marker: marker,
+            item_ranges: Vec::new(),
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+/// A lazily-built seek index over a DICOM data set, letting callers jump
+/// straight to an element's marker by tag path instead of decoding the
+/// whole stream up front.
+#[derive(Debug, Clone, Default)]
+pub struct DatasetIndex {
+    roots: BTreeMap<TagPath, IndexNode>,
+}
+
+impl DatasetIndex {
+    /// Perform a single lazy pass over `source`, collecting every element
+    /// marker into a tree keyed by tag path.
+    pub fn build<S>(source: S, ts: &TransferSyntax, cs: SpecificCharacterSet) -> Result<Self>
+    where
+        S: ReadSeek,
+    {
+        let mut iter: LazyDicomElementIterator<S, (), DynamicDicomParser> =
+            LazyDicomElementIterator::new(source, DynamicDicomParser::new_with(ts, cs)?);
+
+        let mut index = DatasetIndex::default();
+        let mut stack: Vec<OpenSequence> = Vec::new();
+
+        while let Some(marker) = iter.next() {
+            let marker = marker?;
+            match marker.tag() {
+                ITEM_TAG => {
+                    if let Some(frame) = stack.last_mut() {
+                        // Set `item` on the sequence's own last segment
+                        // rather than appending a new one, so this reads
+                        // as `(group,elem)[n]`, not `(group,elem)/(group,elem)[n]`.
+                        let item_path = frame.path.with_item(frame.next_item);
+                        if let Some(node) = index.node_mut(&frame.path) {
+                            node.item_ranges.push(ItemRange {
+                                start: marker.pos,
+                                end: None,
+                            });
+                        }
+                        // Seed the item's own container node with this
+                        // ITEM_TAG's own marker (its position is the
+                        // start of the item's content), so it isn't left
+                        // seeded by whichever child happens to be
+                        // inserted first.
+                        index.insert(&item_path, marker);
+                        frame.item_container = Some(item_path);
+                        frame.next_item += 1;
+                    }
+                }
+                ITEM_DELIMITER_TAG => {
+                    if let Some(frame) = stack.last_mut() {
+                        if let Some(node) = index.node_mut(&frame.path) {
+                            if let Some(range) = node.item_ranges.last_mut() {
+                                range.end = Some(marker.pos);
+                            }
+                        }
+                        frame.item_container = None;
+                    }
+                }
+                SEQUENCE_DELIMITER_TAG => {
+                    stack.pop();
+                }
+                _ => {
+                    let segment = PathSegment {
+                        tag: marker.tag(),
+                        item: None,
+                    };
+                    let path = match stack.last() {
+                        Some(frame) => match &frame.item_container {
+                            Some(container) => container.child(segment),
+                            None => TagPath(vec![segment]),
+                        },
+                        None => TagPath(vec![segment]),
+                    };
+                    index.insert(&path, marker);
+                    if marker.vr() == ::data::VR::SQ {
+                        stack.push(OpenSequence {
+                            path: path,
+                            next_item: 0,
+                            item_container: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(index)
+    }
+
+    fn insert(&mut self, path: &TagPath, marker: DicomElementMarker) {
+        let mut levels = path.0.iter();
+        let first = match levels.next() {
+            Some(s) => s,
+            None => return,
+        };
+        let mut current_path = TagPath(vec![*first]);
+        let mut node = self
+            .roots
+            .entry(current_path.clone())
+            .or_insert_with(|| IndexNode::new(marker));
+        for segment in levels {
+            current_path = current_path.child(*segment);
+            node = node
+                .children
+                .entry(current_path.clone())
+                .or_insert_with(|| IndexNode::new(marker));
+        }
+        node.marker = marker;
+    }
+
+    fn node_mut(&mut self, path: &TagPath) -> Option<&mut IndexNode> {
+        let mut levels = path.0.iter();
+        let first = levels.next()?;
+        let mut node = self.roots.get_mut(&TagPath(vec![*first]))?;
+        let mut current_path = TagPath(vec![*first]);
+        for segment in levels {
+            current_path = current_path.child(*segment);
+            node = node.children.get_mut(&current_path)?;
+        }
+        Some(node)
+    }
+
+    fn node(&self, path: &TagPath) -> Option<&IndexNode> {
+        let mut levels = path.0.iter();
+        let first = levels.next()?;
+        let mut node = self.roots.get(&TagPath(vec![*first]))?;
+        let mut current_path = TagPath(vec![*first]);
+        for segment in levels {
+            current_path = current_path.child(*segment);
+            node = node.children.get(&current_path)?;
+        }
+        Some(node)
+    }
+
+    /// Look up the marker for the element addressed by `path`.
+    pub fn seek_to(&self, path: &TagPath) -> Option<&DicomElementMarker> {
+        self.node(path).map(|node| &node.marker)
+    }
+
+    /// Iterate over the direct children of the node at `path`. Passing an
+    /// empty path iterates over the top-level nodes of the index: the
+    /// top-level elements of the data set and, for each top-level
+    /// sequence, one node per item (`(gggg,eeee)[n]`). An item's node is a
+    /// sibling of its sequence's node, not a child of it, so the children
+    /// of a sequence node do not include its items; pass the item's path
+    /// to iterate over the elements inside it.
+    pub fn children<'a>(&'a self, path: &TagPath) -> Box<Iterator<Item = &'a IndexNode> + 'a> {
+        if path.0.is_empty() {
+            Box::new(self.roots.values())
+        } else {
+            match self.node(path) {
+                Some(node) => Box::new(node.children.values()),
+                None => Box::new(::std::iter::empty()),
+            }
+        }
+    }
+
+    /// Move `source` to the start of the value of the element addressed
+    /// by `path` (the position recorded on its marker, just past its
+    /// header), so that its value can be read on demand.
+    pub fn move_to_start<S: Seek>(&self, path: &TagPath, source: &mut S) -> Result<()> {
+        let marker = self
+            .seek_to(path)
+            .ok_or_else(|| Error::from(::std::io::Error::new(
+                ::std::io::ErrorKind::NotFound,
+                format!("no element at path {}", path),
+            )))?;
+        marker.move_to_start(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PathSegment, TagPath};
+    use data::Tag;
+
+    #[test]
+    fn with_item_sets_last_segment_instead_of_appending() {
+        let sequence_path = TagPath(vec![PathSegment {
+            tag: Tag(0x0008, 0x1140),
+            item: None,
+        }]);
+
+        let item_path = sequence_path.with_item(0);
+        assert_eq!(item_path.0.len(), 1);
+        assert_eq!(format!("{}", item_path), "(0008,1140)[0]");
+
+        let child_path = item_path.child(PathSegment {
+            tag: Tag(0x0008, 0x1155),
+            item: None,
+        });
+        assert_eq!(format!("{}", child_path), "(0008,1140)[0]/(0008,1155)");
+    }
+}
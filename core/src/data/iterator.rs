@@ -7,6 +7,8 @@ use std::iter::Iterator;
 use transfer_syntax::TransferSyntax;
 use data::{DataElement, DataElementHeader, Header, SequenceItemHeader};
 use data::parser::{DicomParser, DynamicDicomParser, Parse};
+use data::parser_state::SequenceDepthTracker;
+use data::recovery::{resync, RecoveryPolicy, RecoveryState, ResyncOutcome};
 use data::text::SpecificCharacterSet;
 use data::value::DicomValue;
 use util::{ReadSeek, SeekInterval};
@@ -20,9 +22,7 @@ use data::Tag;
 pub struct DicomElementIterator<S, P> {
     source: S,
     parser: P,
-    depth: u32,
-    in_sequence: bool,
-    hard_break: bool,
+    state: SequenceDepthTracker,
 }
 
 fn is_parse<S: ?Sized + Read, P>(_: &P) where P: Parse<S> {}
@@ -42,9 +42,7 @@ impl<'s, S: 's> DicomElementIterator<S, DynamicDicomParser> {
         Ok(DicomElementIterator {
             source: source,
             parser: parser,
-            depth: 0,
-            in_sequence: false,
-            hard_break: false,
+            state: SequenceDepthTracker::new(),
         })
     }
 }
@@ -59,9 +57,7 @@ where
         DicomElementIterator {
             source: source,
             parser: parser,
-            depth: 0,
-            in_sequence: false,
-            hard_break: false,
+            state: SequenceDepthTracker::new(),
         }
     }
 }
@@ -93,28 +89,17 @@ where
     type Item = Result<DataElement>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.hard_break {
+        if self.state.is_done() {
             return None;
         }
-        if self.in_sequence {
+        if self.state.expects_item_header() {
             match self.parser.decode_item_header(&mut self.source) {
-                Ok(header) => match header {
-                    header @ SequenceItemHeader::Item { .. } => {
-                        self.in_sequence = false;
-                        Some(self.create_item_marker(header))
-                    }
-                    SequenceItemHeader::ItemDelimiter => {
-                        self.in_sequence = true;
-                        Some(self.create_item_marker(header))
-                    }
-                    SequenceItemHeader::SequenceDelimiter => {
-                        self.depth -= 1;
-                        self.in_sequence = false;
-                        Some(self.create_item_marker(header))
-                    }
-                },
+                Ok(header) => {
+                    self.state.after_item_header(&header);
+                    Some(self.create_item_marker(header))
+                }
                 Err(e) => {
-                    self.hard_break = true;
+                    self.state.set_hard_break();
                     Some(Err(Error::from(e)))
                 }
             }
@@ -131,14 +116,11 @@ where
                 }
                 Ok(header) => {
                     // check if SQ
-                    if header.vr() == VR::SQ {
-                        self.in_sequence = true;
-                        self.depth += 1;
-                    }
+                    self.state.after_element_header(header.vr() == VR::SQ);
                     Some(self.read_element(header))
                 }
                 Err(e) => {
-                    self.hard_break = true;
+                    self.state.set_hard_break();
                     Some(Err(Error::from(e)))
                 }
             }
@@ -146,15 +128,86 @@ where
     }
 }
 
+impl<'s, S: 's, P> DicomElementIterator<S, P>
+where
+    S: Read + Seek,
+    P: Parse<Read + 's>,
+{
+    /// Opt into a lenient, resynchronizing parse mode: rather than
+    /// terminating on the first decode error, the returned iterator will
+    /// scan forward for the next plausible element boundary and resume
+    /// from there, yielding a `Result::Err` describing the skipped region
+    /// instead of ending the stream. The scan starts wherever the failed
+    /// decode left the source; it does not seek back to the end of the
+    /// last element that was read cleanly. `policy` bounds how much may be
+    /// scanned and how many consecutive recoveries are allowed before the
+    /// iterator gives up for good.
+    pub fn with_recovery(self, policy: RecoveryPolicy) -> RecoveringDicomElementIterator<S, P> {
+        RecoveringDicomElementIterator {
+            inner: self,
+            policy: policy,
+            state: RecoveryState::new(),
+        }
+    }
+}
+
+/// A `DicomElementIterator` wrapped in a lenient, error-tolerant parse
+/// mode. See `DicomElementIterator::with_recovery`.
+#[derive(Debug)]
+pub struct RecoveringDicomElementIterator<S, P> {
+    inner: DicomElementIterator<S, P>,
+    policy: RecoveryPolicy,
+    state: RecoveryState,
+}
+
+impl<'s, S: 's, P> Iterator for RecoveringDicomElementIterator<S, P>
+where
+    S: Read + Seek,
+    P: Parse<Read + 's>,
+{
+    type Item = Result<DataElement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next() {
+            None => None,
+            Some(Ok(element)) => {
+                self.state.record_clean_read();
+                Some(Ok(element))
+            }
+            Some(Err(e)) => {
+                if !self.state.can_recover(&self.policy) {
+                    return Some(Err(e));
+                }
+                match resync(&mut self.inner.source, &self.policy) {
+                    Ok(ResyncOutcome::Resynced { skipped }) => {
+                        self.state.record_recovery();
+                        // The corrupt region may have spanned sequence
+                        // boundaries we can no longer account for, so the
+                        // safest assumption is that we are back at the
+                        // top level.
+                        self.inner.state = SequenceDepthTracker::new();
+                        Some(Err(Error::from(::std::io::Error::new(
+                            ::std::io::ErrorKind::InvalidData,
+                            format!(
+                                "skipped {} byte(s) while recovering from a decode error: {}",
+                                skipped, e
+                            ),
+                        ))))
+                    }
+                    Ok(ResyncOutcome::GaveUp) | Err(_) => Some(Err(e)),
+                }
+            }
+        }
+    }
+}
+
 /// An iterator for retrieving DICOM object element markers from a random
 /// access data source.
 #[derive(Debug)]
 pub struct LazyDicomElementIterator<S, DS, P> {
     source: S,
     parser: P,
-    depth: u32,
-    in_sequence: bool,
-    hard_break: bool,
+    state: SequenceDepthTracker,
     phantom: PhantomData<DS>,
 }
 
@@ -171,9 +224,7 @@ impl<'s> LazyDicomElementIterator<&'s mut ReadSeek, &'s mut Read, DynamicDicomPa
         Ok(LazyDicomElementIterator {
             source: source,
             parser: parser,
-            depth: 0,
-            in_sequence: false,
-            hard_break: false,
+            state: SequenceDepthTracker::new(),
             phantom: PhantomData,
         })
     }
@@ -188,9 +239,7 @@ where
         LazyDicomElementIterator {
             source: source,
             parser: parser,
-            depth: 0,
-            in_sequence: false,
-            hard_break: false,
+            state: SequenceDepthTracker::new(),
             phantom: PhantomData,
         }
     }
@@ -210,7 +259,7 @@ where
                 pos: pos,
             }),
             Err(e) => {
-                self.hard_break = true;
+                self.state.set_hard_break();
                 Err(Error::from(e))
             }
         }
@@ -223,7 +272,7 @@ where
                 pos: pos,
             }),
             Err(e) => {
-                self.hard_break = true;
+                self.state.set_hard_break();
                 Err(Error::from(e))
             }
         }
@@ -238,28 +287,17 @@ where
     type Item = Result<DicomElementMarker>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.hard_break {
+        if self.state.is_done() {
             return None;
         }
-        if self.in_sequence {
+        if self.state.expects_item_header() {
             match self.parser.decode_item_header(&mut self.source) {
-                Ok(header) => match header {
-                    header @ SequenceItemHeader::Item { .. } => {
-                        self.in_sequence = false;
-                        Some(self.create_item_marker(header))
-                    }
-                    SequenceItemHeader::ItemDelimiter => {
-                        self.in_sequence = true;
-                        Some(self.create_item_marker(header))
-                    }
-                    SequenceItemHeader::SequenceDelimiter => {
-                        self.depth -= 1;
-                        self.in_sequence = false;
-                        Some(self.create_item_marker(header))
-                    }
-                },
+                Ok(header) => {
+                    self.state.after_item_header(&header);
+                    Some(self.create_item_marker(header))
+                }
                 Err(e) => {
-                    self.hard_break = true;
+                    self.state.set_hard_break();
                     Some(Err(Error::from(e)))
                 }
             }
@@ -276,14 +314,11 @@ where
                 }
                 Ok(header) => {
                     // check if SQ
-                    if header.vr() == VR::SQ {
-                        self.in_sequence = true;
-                        self.depth += 1;
-                    }
+                    self.state.after_element_header(header.vr() == VR::SQ);
                     Some(self.create_element_marker(header))
                 }
                 Err(e) => {
-                    self.hard_break = true;
+                    self.state.set_hard_break();
                     Some(Err(Error::from(e)))
                 }
             }
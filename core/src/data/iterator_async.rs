@@ -0,0 +1,161 @@
+//! An asynchronous counterpart to `DicomElementIterator`, for driving the
+//! same header/value decoding loop over non-blocking sources (e.g. a
+//! server streaming DICOM over a network socket).
+//!
+//! This module is only available when the `async` feature is enabled. It
+//! holds an async counterpart of the eager `DicomElementIterator` only;
+//! there is no async version of the lazy, marker-only
+//! `LazyDicomElementIterator`, though `poll_position` exposes the source
+//! position. Both the sync and async front-ends delegate to the same
+//! `SequenceDepthTracker` state machine, so the
+//! `in_sequence`/`depth`/`hard_break` bookkeeping is defined only once.
+#![cfg(feature = "async")]
+
+use futures::io::{AsyncRead, AsyncSeek};
+use futures::stream::Stream;
+use futures::task::{Context, Poll};
+use std::pin::Pin;
+
+use data::parser::{AsyncParse, DynamicDicomParser};
+use data::parser_state::SequenceDepthTracker;
+use data::text::SpecificCharacterSet;
+use data::value::DicomValue;
+use data::{DataElement, DataElementHeader, Header, SequenceItemHeader};
+use data::Tag;
+use data::VR;
+use error::{Error, Result};
+use transfer_syntax::TransferSyntax;
+
+/// An asynchronous iterator for retrieving DICOM data elements from a
+/// source implementing `AsyncRead + AsyncSeek`. This is the non-blocking
+/// counterpart to `DicomElementIterator`, yielding a `Stream` instead of a
+/// plain `Iterator`.
+pub struct AsyncDicomElementIterator<S, P> {
+    source: S,
+    parser: P,
+    state: SequenceDepthTracker,
+    /// Set while a value read future is in flight, so `poll_next` can be
+    /// called again without losing the in-progress header.
+    pending_header: Option<DataElementHeader>,
+}
+
+impl<'s, S: 's> AsyncDicomElementIterator<S, DynamicDicomParser> {
+    /// Create a new asynchronous iterator with the given source, transfer
+    /// syntax and specific character set.
+    pub fn new_with(
+        source: S,
+        ts: &TransferSyntax,
+        cs: SpecificCharacterSet,
+    ) -> Result<Self> {
+        let parser = DynamicDicomParser::new_with(ts, cs)?;
+
+        Ok(AsyncDicomElementIterator {
+            source: source,
+            parser: parser,
+            state: SequenceDepthTracker::new(),
+            pending_header: None,
+        })
+    }
+}
+
+impl<S, P> AsyncDicomElementIterator<S, P>
+where
+    S: AsyncRead + AsyncSeek + Unpin,
+    P: AsyncParse<S> + Unpin,
+{
+    /// Create a new asynchronous iterator with the given parser.
+    pub fn new(source: S, parser: P) -> Self {
+        AsyncDicomElementIterator {
+            source: source,
+            parser: parser,
+            state: SequenceDepthTracker::new(),
+            pending_header: None,
+        }
+    }
+
+    /// Obtain the inner source's position in the stream, as tracked by
+    /// `AsyncSeek`. This mirrors `LazyDicomElementIterator::get_position`
+    /// for callers that only need marker-style bookkeeping.
+    pub fn poll_position(&mut self, cx: &mut Context) -> Poll<Result<u64>> {
+        Pin::new(&mut self.source)
+            .poll_seek(cx, ::std::io::SeekFrom::Current(0))
+            .map_err(Error::from)
+    }
+
+    fn item_marker(header: SequenceItemHeader) -> DataElement {
+        DataElement {
+            header: header.into(),
+            value: DicomValue::Empty,
+        }
+    }
+}
+
+impl<S, P> Stream for AsyncDicomElementIterator<S, P>
+where
+    S: AsyncRead + AsyncSeek + Unpin,
+    P: AsyncParse<S> + Unpin,
+{
+    type Item = Result<DataElement>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.state.is_done() {
+            return Poll::Ready(None);
+        }
+
+        // A decoded element header must be turned into a value (and
+        // yielded) before `state` is allowed to decide what comes next —
+        // otherwise an SQ header's own `after_element_header(true)` call
+        // flips `expects_item_header()` to true ahead of the element
+        // that triggered it, so the branch below would skip straight to
+        // decoding the first item header and silently drop the SQ
+        // element itself, leaving `pending_header` stale for whatever
+        // polls next.
+        if let Some(header) = this.pending_header.take() {
+            return match Pin::new(&mut this.parser).poll_read_value(cx, &mut this.source, &header) {
+                Poll::Ready(Ok(value)) => Poll::Ready(Some(Ok(DataElement { header, value }))),
+                Poll::Ready(Err(e)) => {
+                    this.state.set_hard_break();
+                    Poll::Ready(Some(Err(Error::from(e))))
+                }
+                Poll::Pending => {
+                    this.pending_header = Some(header);
+                    Poll::Pending
+                }
+            };
+        }
+
+        if this.state.expects_item_header() {
+            return match Pin::new(&mut this.parser).poll_decode_item_header(cx, &mut this.source) {
+                Poll::Ready(Ok(header)) => {
+                    this.state.after_item_header(&header);
+                    Poll::Ready(Some(Ok(Self::item_marker(header))))
+                }
+                Poll::Ready(Err(e)) => {
+                    this.state.set_hard_break();
+                    Poll::Ready(Some(Err(Error::from(e))))
+                }
+                Poll::Pending => Poll::Pending,
+            };
+        }
+
+        match Pin::new(&mut this.parser).poll_decode_header(cx, &mut this.source) {
+            Poll::Ready(Ok(header)) => {
+                if header.tag() != Tag(0x0008, 0x0005) {
+                    this.state.after_element_header(header.vr() == VR::SQ);
+                }
+                this.pending_header = Some(header);
+                // Re-enter immediately to drive the value read; the
+                // executor will poll again since we haven't returned
+                // `Pending` without registering a waker ourselves.
+                Stream::poll_next(Pin::new(this), cx)
+            }
+            Poll::Ready(Err(e)) => {
+                this.state.set_hard_break();
+                Poll::Ready(Some(Err(Error::from(e))))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
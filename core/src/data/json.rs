@@ -0,0 +1,38 @@
+//! Export of a decoded element stream to the standard DICOM JSON model,
+//! and the symmetric import path.
+//!
+//! Available behind the `json` feature. See `data::element_model` for the
+//! tree construction shared with the CBOR front-end in `data::cbor`.
+#![cfg(feature = "json")]
+
+use std::io::{Read, Write};
+
+use data::element_model::{self, JsonDataSet};
+use data::DataElement;
+use error::{Error, Result};
+
+/// Consume a stream of decoded elements (as yielded by
+/// `DicomElementIterator`) and write it out as a DICOM JSON model
+/// document, nesting `VR::SQ` elements into arrays of item objects.
+pub fn to_writer<W, I>(writer: W, elements: I) -> Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = Result<DataElement>>,
+{
+    let data_set = element_model::build_data_set(elements, false)?;
+    ::serde_json::to_writer(writer, &data_set).map_err(Error::from)
+}
+
+/// Parse a DICOM JSON model document into its tree representation. Use
+/// `data::element_model::flatten_data_set` to turn the result back into a
+/// flat stream of `DataElement`s. Note that this is not yet a drop-in
+/// source for `data::codec::rewrite`, which writes `EncodableItem`s (raw,
+/// already-encoded value bytes) rather than `DataElement`s (typed,
+/// decoded values) — bridging the two still needs a value-to-bytes
+/// encoder that doesn't exist yet.
+pub fn from_reader<R>(reader: R) -> Result<JsonDataSet>
+where
+    R: Read,
+{
+    ::serde_json::from_reader(reader).map_err(Error::from)
+}
@@ -0,0 +1,83 @@
+//! Shared state machine for driving header/value decoding over a DICOM
+//! data set, used by both the blocking (`DicomElementIterator`,
+//! `LazyDicomElementIterator`) and asynchronous (`AsyncDicomElementIterator`)
+//! front-ends so that the `in_sequence`/`depth`/`hard_break` bookkeeping is
+//! defined in exactly one place.
+use data::{DataElementHeader, SequenceItemHeader};
+
+/// What a front-end should do next after decoding a header.
+#[derive(Debug)]
+pub enum Step {
+    /// A plain element header was read; the front-end should now read its
+    /// value (or, for the lazy variants, just record the marker).
+    Element(DataElementHeader),
+    /// A sequence item header (item, item delimiter or sequence delimiter)
+    /// was read; no value bytes follow.
+    Item(SequenceItemHeader),
+}
+
+/// Tracks the `in_sequence`/`depth` state shared by every element iterator
+/// in this module, independently of how the underlying bytes are fetched.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SequenceDepthTracker {
+    depth: u32,
+    in_sequence: bool,
+    hard_break: bool,
+}
+
+impl SequenceDepthTracker {
+    /// Create a tracker in its initial state, outside of any sequence.
+    pub fn new() -> Self {
+        SequenceDepthTracker {
+            depth: 0,
+            in_sequence: false,
+            hard_break: false,
+        }
+    }
+
+    /// Whether the iterator has already terminated and should yield `None`.
+    pub fn is_done(&self) -> bool {
+        self.hard_break
+    }
+
+    /// Whether the next read should be a sequence item header rather than
+    /// a plain data element header.
+    pub fn expects_item_header(&self) -> bool {
+        self.in_sequence
+    }
+
+    /// The current nesting depth in sequences.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Mark the tracker as having hit an unrecoverable error.
+    pub fn set_hard_break(&mut self) {
+        self.hard_break = true;
+    }
+
+    /// Record the effect of having just decoded a plain data element
+    /// header, entering a sequence if the header's VR is `SQ`.
+    pub fn after_element_header(&mut self, is_sq: bool) {
+        if is_sq {
+            self.in_sequence = true;
+            self.depth += 1;
+        }
+    }
+
+    /// Record the effect of having just decoded a sequence item header.
+    pub fn after_item_header(&mut self, header: &SequenceItemHeader) {
+        match *header {
+            SequenceItemHeader::Item { .. } => {
+                self.in_sequence = false;
+            }
+            SequenceItemHeader::ItemDelimiter => {
+                self.in_sequence = true;
+            }
+            SequenceItemHeader::SequenceDelimiter => {
+                self.depth -= 1;
+                self.in_sequence = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Support for resynchronizing an element iterator after a decode error,
+//! instead of terminating the whole read on the first truncated or
+//! malformed element.
+use std::io::{Read, Seek, SeekFrom};
+
+/// The two-letter VR codes recognized when scanning for a plausible
+/// element boundary. Kept in the same order as the VR table so the two
+/// are easy to compare; a new VR must be added to this list as well.
+const VALID_VR_CODES: &'static [&'static [u8; 2]] = &[
+    b"AE", b"AS", b"AT", b"CS", b"DA", b"DS", b"DT", b"FL", b"FD", b"IS",
+    b"LO", b"LT", b"OB", b"OF", b"OW", b"PN", b"SH", b"SL", b"SQ", b"SS",
+    b"ST", b"TM", b"UI", b"UL", b"UN", b"US", b"UT",
+];
+
+fn is_valid_vr(bytes: &[u8]) -> bool {
+    VALID_VR_CODES.iter().any(|vr| vr.as_ref() == bytes)
+}
+
+/// Governs how an iterator in recovery mode scans for the next plausible
+/// element boundary after hitting a decode error.
+#[derive(Debug, Clone, Copy)]
+pub struct RecoveryPolicy {
+    /// The maximum number of bytes to scan, starting from wherever the
+    /// failed decode left the source, before giving up on the current
+    /// resync attempt.
+    pub max_scan_bytes: u64,
+    /// The maximum number of *consecutive* resyncs the iterator is
+    /// allowed before it gives up entirely and hard-breaks like the
+    /// non-lenient iterator would. A clean element read in between
+    /// resets the count, so this bounds a run of back-to-back failures,
+    /// not the lifetime total.
+    pub max_recoveries: u32,
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        RecoveryPolicy {
+            max_scan_bytes: 64 * 1024,
+            max_recoveries: 16,
+        }
+    }
+}
+
+/// Tracks how many *consecutive* resyncs have happened so far — a clean
+/// element read in between resets the count back to zero, so
+/// `max_recoveries` bounds a run of back-to-back failures rather than the
+/// total number of times the iterator ever recovers over its lifetime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecoveryState {
+    recoveries_done: u32,
+}
+
+impl RecoveryState {
+    pub fn new() -> Self {
+        RecoveryState { recoveries_done: 0 }
+    }
+
+    /// Whether another resync attempt is still allowed under `policy`.
+    pub fn can_recover(&self, policy: &RecoveryPolicy) -> bool {
+        self.recoveries_done < policy.max_recoveries
+    }
+
+    pub fn record_recovery(&mut self) {
+        self.recoveries_done += 1;
+    }
+
+    /// Reset the consecutive-recovery count after a clean element read,
+    /// so a later run of failures gets the full budget again instead of
+    /// inheriting whatever was left over from an earlier, unrelated run.
+    pub fn record_clean_read(&mut self) {
+        self.recoveries_done = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecoveryPolicy, RecoveryState};
+
+    #[test]
+    fn clean_read_resets_the_consecutive_recovery_count() {
+        let policy = RecoveryPolicy { max_scan_bytes: 64, max_recoveries: 2 };
+        let mut state = RecoveryState::new();
+
+        state.record_recovery();
+        state.record_recovery();
+        assert!(!state.can_recover(&policy));
+
+        state.record_clean_read();
+        assert!(state.can_recover(&policy));
+    }
+}
+
+/// The outcome of a resync attempt.
+#[derive(Debug)]
+pub enum ResyncOutcome {
+    /// A plausible element boundary was found after skipping `skipped`
+    /// bytes.
+    Resynced {
+        /// The number of bytes skipped to reach the new boundary.
+        skipped: u64,
+    },
+    /// No plausible boundary was found within the policy's scan budget.
+    GaveUp,
+}
+
+/// Scan `source` one byte at a time, looking for a 4-byte little-endian
+/// group/element tag pair with an even group number (the group is not
+/// otherwise checked) immediately followed by a valid two-letter VR
+/// code, stopping after
+/// `policy.max_scan_bytes` bytes have been consumed.
+///
+/// On success, `source` is seeked back so it is positioned right at the
+/// start of the recovered tag, ready for the parser to decode a header
+/// from scratch.
+pub fn resync<S: Read + Seek>(
+    source: &mut S,
+    policy: &RecoveryPolicy,
+) -> ::std::io::Result<ResyncOutcome> {
+    let mut window = [0u8; 6];
+    let mut filled = 0usize;
+    let mut skipped = 0u64;
+    let mut byte = [0u8; 1];
+
+    while skipped < policy.max_scan_bytes {
+        if source.read(&mut byte)? == 0 {
+            return Ok(ResyncOutcome::GaveUp);
+        }
+        if filled < window.len() {
+            window[filled] = byte[0];
+            filled += 1;
+        } else {
+            for i in 1..window.len() {
+                window[i - 1] = window[i];
+            }
+            window[window.len() - 1] = byte[0];
+        }
+
+        if filled == window.len() {
+            let group = u16::from(window[0]) | (u16::from(window[1]) << 8);
+            if group % 2 == 0 && is_valid_vr(&window[4..6]) {
+                source.seek(SeekFrom::Current(-(window.len() as i64)))?;
+                return Ok(ResyncOutcome::Resynced {
+                    skipped: skipped + 1 - window.len() as u64,
+                });
+            }
+        }
+        skipped += 1;
+    }
+
+    Ok(ResyncOutcome::GaveUp)
+}
+
+#[cfg(test)]
+mod resync_tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn tag_and_vr(group: u16, element: u16, vr: &[u8; 2]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&group.to_le_bytes());
+        bytes.extend_from_slice(&element.to_le_bytes());
+        bytes.extend_from_slice(vr);
+        bytes
+    }
+
+    #[test]
+    fn finds_a_clean_boundary_immediately() {
+        let data = tag_and_vr(0x0008, 0x0005, b"CS");
+        let mut cursor = Cursor::new(data);
+        let policy = RecoveryPolicy { max_scan_bytes: 64, max_recoveries: 1 };
+
+        match resync(&mut cursor, &policy).unwrap() {
+            ResyncOutcome::Resynced { skipped } => assert_eq!(skipped, 0),
+            ResyncOutcome::GaveUp => panic!("expected a resync"),
+        }
+        assert_eq!(cursor.position(), 0);
+    }
+
+    #[test]
+    fn skips_garbage_bytes_before_a_boundary() {
+        let mut data = vec![0xFF, 0xFF, 0xFF];
+        data.extend(tag_and_vr(0x0008, 0x0005, b"CS"));
+        let mut cursor = Cursor::new(data);
+        let policy = RecoveryPolicy { max_scan_bytes: 64, max_recoveries: 1 };
+
+        match resync(&mut cursor, &policy).unwrap() {
+            ResyncOutcome::Resynced { skipped } => assert_eq!(skipped, 3),
+            ResyncOutcome::GaveUp => panic!("expected a resync"),
+        }
+        assert_eq!(cursor.position(), 3);
+    }
+
+    #[test]
+    fn gives_up_once_the_scan_budget_is_exhausted() {
+        // An odd (0xFFFF) group never passes the even-group check, so no
+        // byte offset in here can look like a valid boundary.
+        let data = vec![0xFFu8; 64];
+        let mut cursor = Cursor::new(data);
+        let policy = RecoveryPolicy { max_scan_bytes: 16, max_recoveries: 1 };
+
+        match resync(&mut cursor, &policy).unwrap() {
+            ResyncOutcome::GaveUp => {}
+            ResyncOutcome::Resynced { .. } => panic!("expected to give up"),
+        }
+    }
+}